@@ -5,7 +5,10 @@ use anyhow::Result;
 use argh::FromArgs;
 use chrono::NaiveDateTime;
 use log::LevelFilter;
-use sqlar::{with_each_file, FileType};
+use sqlar::{
+    add_with_options, create_with_options, extract_with_options, mount, with_each_file,
+    Compression, CreateOptions, ExtractOptions, FileType, Pattern,
+};
 use tabwriter::TabWriter;
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -25,6 +28,7 @@ enum Subcommand {
     Extract(Extract),
     Create(Create),
     List(List),
+    Mount(Mount),
 }
 
 /// Extract files from archive
@@ -35,10 +39,24 @@ struct Extract {
     #[argh(positional)]
     archive: PathBuf,
 
-    /// destination to extract to (optional).
-    /// Defaults to the archive file name without extension.
+    /// destination to extract to (optional, defaults to the archive file
+    /// name without extension); with --stdout, this is instead the path of
+    /// the single entry inside the archive to stream out
     #[argh(positional)]
     destination: Option<PathBuf>,
+
+    /// include or exclude entries by glob pattern: prefix the pattern with
+    /// `+` to include or `-` to exclude (can be repeated; later --filter
+    /// flags take precedence over earlier ones for a given entry, in the
+    /// order given on the command line)
+    #[argh(option)]
+    filter: Vec<String>,
+
+    /// write the extracted entry to stdout instead of to a file; the
+    /// destination positional is then read as the entry's path inside the
+    /// archive
+    #[argh(switch)]
+    stdout: bool,
 }
 
 /// Create a new archive
@@ -56,6 +74,18 @@ struct Create {
     /// additional files to include
     #[argh(positional)]
     paths: Vec<PathBuf>,
+
+    /// append to an existing archive instead of requiring a new one
+    #[argh(switch, short = 'a')]
+    append: bool,
+
+    /// compression codec: store, deflate, or zstd (default: deflate)
+    #[argh(option, default = "\"deflate\".to_string()")]
+    compression: String,
+
+    /// compression level (deflate: 0-9, default 6; zstd: e.g. 1-22, default 3)
+    #[argh(option)]
+    level: Option<i32>,
 }
 
 /// List contents of archive
@@ -67,6 +97,19 @@ struct List {
     archive: PathBuf,
 }
 
+/// Mount archive read-only as a FUSE filesystem
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "mount")]
+struct Mount {
+    /// archive file
+    #[argh(positional)]
+    archive: PathBuf,
+
+    /// directory to mount the archive at
+    #[argh(positional)]
+    mountpoint: PathBuf,
+}
+
 fn main() {
     match real_main() {
         Ok(()) => {}
@@ -89,6 +132,17 @@ fn real_main() -> Result<()> {
         .init();
 
     match cmd.nested {
+        Subcommand::Extract(x) if x.stdout => {
+            let name = x
+                .destination
+                .as_deref()
+                .and_then(Path::to_str)
+                .ok_or_else(|| anyhow::anyhow!("usage: sqlar x <archive> <entry> --stdout"))?;
+
+            log::info!("Streaming {} from {} to stdout", name, x.archive.display());
+            let stdout = io::stdout();
+            sqlar::extract_one(&x.archive, name, &mut stdout.lock())?;
+        }
         Subcommand::Extract(x) => {
             let archive = &x.archive;
             let destination = x
@@ -103,19 +157,77 @@ fn real_main() -> Result<()> {
                 archive.display(),
                 destination.display()
             );
-            sqlar::extract(&archive, &destination)?
+
+            let mut patterns = Vec::new();
+            let mut any_include = false;
+            for filter in &x.filter {
+                let (sign, pattern) = filter.split_at(1.min(filter.len()));
+                match sign {
+                    "+" => {
+                        patterns.push(Pattern::include(pattern)?);
+                        any_include = true;
+                    }
+                    "-" => patterns.push(Pattern::exclude(pattern)?),
+                    _ => anyhow::bail!(
+                        "--filter patterns must start with '+' (include) or '-' (exclude): {}",
+                        filter
+                    ),
+                }
+            }
+
+            let mut options = ExtractOptions {
+                default_include: !any_include,
+                allow_existing_dirs: !patterns.is_empty(),
+                patterns,
+                ..ExtractOptions::default()
+            };
+
+            let errors = extract_with_options(&archive, &destination, &mut options)?;
+            if !errors.is_empty() {
+                log::warn!("{} entries failed to extract", errors.len());
+            }
         }
         Subcommand::Create(c) => {
             let mut paths = vec![c.path];
             paths.extend_from_slice(&c.paths);
+
+            let compression = match c.compression.as_str() {
+                "store" => Compression::Store,
+                "deflate" => Compression::Deflate {
+                    level: c.level.unwrap_or(6).clamp(0, 9) as u32,
+                },
+                "zstd" => Compression::Zstd {
+                    level: c.level.unwrap_or(3),
+                },
+                other => anyhow::bail!("unknown compression codec: {}", other),
+            };
+            let options = CreateOptions { compression };
+
+            if c.append {
+                log::info!(
+                    "Adding to archive {} files: {:?}",
+                    c.archive.display(),
+                    paths
+                );
+                add_with_options(&c.archive, &paths, &options)?
+            } else {
+                log::info!(
+                    "Creating new archive {} with files: {:?}",
+                    c.archive.display(),
+                    paths
+                );
+                create_with_options(&c.archive, &paths, &options)?
+            }
+        }
+        Subcommand::List(l) => list(&*l.archive)?,
+        Subcommand::Mount(m) => {
             log::info!(
-                "Creating new archive {} with files: {:?}",
-                c.archive.display(),
-                paths
+                "Mounting {} at {}",
+                m.archive.display(),
+                m.mountpoint.display()
             );
-            sqlar::create(&c.archive, &paths)?
+            mount(&m.archive, &m.mountpoint)?
         }
-        Subcommand::List(l) => list(&*l.archive)?,
     }
 
     Ok(())
@@ -0,0 +1,101 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression as ZlibLevel;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{Connection, Error, Result};
+
+/// Compression codec used when writing new entries, selected via
+/// [`crate::CreateOptions::compression`].
+#[derive(Debug, Clone, Copy)]
+pub enum Compression {
+    /// Store entries uncompressed.
+    Store,
+    /// zlib/deflate, the codec classic `sqlar` readers expect.
+    Deflate { level: u32 },
+    /// zstd; smaller and faster than deflate at a comparable ratio, but not
+    /// understood by `sqlar` readers that only know zlib.
+    Zstd { level: i32 },
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Deflate { level: 6 }
+    }
+}
+
+impl Compression {
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Store => data.to_vec(),
+            Compression::Deflate { level } => {
+                let mut enc = ZlibEncoder::new(Vec::new(), ZlibLevel::new(level));
+                enc.write_all(data).expect("writing to a Vec can't fail");
+                enc.finish().expect("writing to a Vec can't fail")
+            }
+            Compression::Zstd { level } => {
+                zstd::encode_all(data, level).expect("writing to a Vec can't fail")
+            }
+        }
+    }
+}
+
+fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Register the `rusty_sqlar_compress`/`rusty_sqlar_uncompress` scalar
+/// functions the `sqlar` INSERT/SELECT statements rely on.
+///
+/// `compression` only governs newly compressed entries; reading always
+/// round-trips every codec this module knows, so archives that mix codecs
+/// across rows (e.g. after `add`-ing with a different `--compression`)
+/// extract correctly.
+pub(crate) fn init(conn: &Connection, compression: Compression) -> Result<()> {
+    conn.create_scalar_function(
+        "rusty_sqlar_compress",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let data = ctx.get_raw(0).as_blob()?;
+            let compressed = compression.compress(data);
+
+            // Canonical sqlar invariant: only keep the compressed blob when
+            // it is strictly smaller, otherwise store the entry raw so
+            // `length(data) == sz` still identifies an uncompressed row.
+            if compressed.len() < data.len() {
+                Ok(compressed)
+            } else {
+                Ok(data.to_vec())
+            }
+        },
+    )?;
+
+    conn.create_scalar_function(
+        "rusty_sqlar_uncompress",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let data = ctx.get_raw(0).as_blob()?;
+            let sz: i64 = ctx.get(1)?;
+
+            if data.len() as i64 == sz {
+                return Ok(data.to_vec());
+            }
+
+            if let Ok(out) = inflate(data) {
+                return Ok(out);
+            }
+            if let Ok(out) = zstd::decode_all(data) {
+                return Ok(out);
+            }
+
+            Err(Error::UserFunctionError(
+                "sqlar entry uses an unrecognized compression codec".into(),
+            ))
+        },
+    )
+}
@@ -1,6 +1,7 @@
 use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::os::unix::fs::PermissionsExt;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::time;
 
@@ -9,10 +10,14 @@ use rusqlite::params;
 pub use rusqlite::{Connection, Result};
 use walkdir::WalkDir;
 
-mod extract;
+mod compress;
+mod mount;
+
+pub use compress::Compression;
+pub use mount::{mount, SqlarFs};
 
 const SCHEMA: &str = r#"
-CREATE TABLE sqlar(
+CREATE TABLE IF NOT EXISTS sqlar(
     name TEXT PRIMARY KEY,  -- name of the file
     mode INT,               -- access permissions
     mtime INT,              -- last modification time
@@ -32,10 +37,11 @@ pub struct Entry {
     pub data: Option<Vec<u8>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
     File,
     Dir,
+    Symlink,
     Unsupported,
 }
 
@@ -47,6 +53,8 @@ const S_IFMT: u32 = 0o0170000;
 const S_IFREG: u32 = 0o0100000;
 /// directory
 const S_IFDIR: u32 = 0o0040000;
+/// symbolic link
+const S_IFLNK: u32 = 0o0120000;
 
 impl From<u32> for FileType {
     fn from(mode: u32) -> FileType {
@@ -56,6 +64,9 @@ impl From<u32> for FileType {
         if mode & S_IFMT == S_IFDIR {
             return FileType::Dir;
         }
+        if mode & S_IFMT == S_IFLNK {
+            return FileType::Symlink;
+        }
 
         FileType::Unsupported
     }
@@ -63,6 +74,9 @@ impl From<u32> for FileType {
 
 impl From<fs::FileType> for FileType {
     fn from(ft: fs::FileType) -> FileType {
+        if ft.is_symlink() {
+            return FileType::Symlink;
+        }
         if ft.is_file() {
             return FileType::File;
         }
@@ -119,34 +133,224 @@ pub fn with_each_file(
     Ok(())
 }
 
+/// A single include/exclude glob pattern used to select which archive
+/// entries [`extract_with_options`] restores.
+///
+/// Supports `*`, `**` and `?` glob semantics, matched against the entry's
+/// stored relative path.
+pub struct Pattern {
+    pattern: glob::Pattern,
+    include: bool,
+}
+
+impl Pattern {
+    /// Match entries against `pattern`, keeping the matches.
+    pub fn include(pattern: &str) -> std::result::Result<Pattern, glob::PatternError> {
+        Ok(Pattern {
+            pattern: glob::Pattern::new(pattern)?,
+            include: true,
+        })
+    }
+
+    /// Match entries against `pattern`, dropping the matches.
+    pub fn exclude(pattern: &str) -> std::result::Result<Pattern, glob::PatternError> {
+        Ok(Pattern {
+            pattern: glob::Pattern::new(pattern)?,
+            include: false,
+        })
+    }
+
+    fn matches(&self, name: &str) -> Option<bool> {
+        if self.pattern.matches(name) {
+            Some(self.include)
+        } else {
+            None
+        }
+    }
+}
+
+/// An error encountered while restoring a single entry, handed to
+/// [`ExtractOptions::on_error`].
+#[derive(Debug)]
+pub struct ExtractError {
+    /// name of the entry being restored, as stored in the archive.
+    pub name: String,
+    /// the operation that failed, e.g. `"create file"` or `"set permissions"`.
+    pub operation: &'static str,
+    pub source: io::Error,
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "failed to {} for {}: {}",
+            self.operation, self.name, self.source
+        )
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// What extraction should do after [`ExtractOptions::on_error`] has been
+/// notified of a failed entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractAction {
+    /// Skip this entry and keep extracting the rest of the archive.
+    Continue,
+    /// Stop extracting further entries.
+    Abort,
+}
+
+/// Per-entry error handler used by [`ExtractOptions::on_error`].
+pub type ErrorHandler = Box<dyn FnMut(&ExtractError) -> ExtractAction>;
+
+/// Options controlling which entries [`extract_with_options`] restores and how.
+pub struct ExtractOptions {
+    /// ordered include/exclude patterns; the last pattern that matches an
+    /// entry's name wins.
+    pub patterns: Vec<Pattern>,
+    /// whether an entry is extracted when no pattern matches it.
+    pub default_include: bool,
+    /// tolerate a destination directory that already exists instead of
+    /// treating it as an error, which partial re-extraction runs into.
+    pub allow_existing_dirs: bool,
+    /// called whenever creating a file/directory/symlink, or setting its
+    /// mtime or permissions, fails. Defaults to logging a warning and
+    /// continuing with the next entry.
+    pub on_error: ErrorHandler,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            patterns: Vec::new(),
+            default_include: true,
+            allow_existing_dirs: false,
+            on_error: Box::new(|e: &ExtractError| {
+                log::warn!("{}", e);
+                ExtractAction::Continue
+            }),
+        }
+    }
+}
+
+impl ExtractOptions {
+    fn should_extract(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find_map(|pattern| pattern.matches(name))
+            .unwrap_or(self.default_include)
+    }
+}
+
 /// Extract all files from the SQLar at `path` into `dest`
-pub fn extract(path: &Path, dest: &Path) -> Result<()> {
+pub fn extract(path: &Path, dest: &Path) -> Result<Vec<ExtractError>> {
+    extract_with_options(path, dest, &mut ExtractOptions::default())
+}
+
+/// Extract files from the SQLar at `path` into `dest`, restricted to the
+/// entries selected by `options`. Returns every per-entry error handed to
+/// `options.on_error`, including the one it answered with
+/// [`ExtractAction::Abort`], if any.
+pub fn extract_with_options(
+    path: &Path,
+    dest: &Path,
+    options: &mut ExtractOptions,
+) -> Result<Vec<ExtractError>> {
     fs::create_dir_all(&dest).expect("can't create target directory");
 
     let db = Connection::open(path)?;
-    extract::init(&db)?;
+    compress::init(&db, Compression::default())?;
+
+    let mut errors = Vec::new();
+    let mut aborted = false;
 
     with_each_file(&db, true, |entry| {
+        if aborted {
+            return Ok(());
+        }
+
         if Path::new(&entry.name).is_absolute() {
             log::warn!("absolute file path found: {}, skipping.", entry.name);
             return Ok(());
         }
 
+        if !options.should_extract(&entry.name) {
+            log::info!("Skipping {} (excluded by pattern)", entry.name);
+            return Ok(());
+        }
+
         let path = dest.join(&entry.name);
 
+        macro_rules! on_error {
+            ($operation:expr, $source:expr) => {{
+                let err = ExtractError {
+                    name: entry.name.clone(),
+                    operation: $operation,
+                    source: $source,
+                };
+                if (options.on_error)(&err) == ExtractAction::Abort {
+                    aborted = true;
+                }
+                errors.push(err);
+                return Ok(());
+            }};
+        }
+
+        // A selective extraction (via `options.patterns`) can select a file
+        // without its containing directory entries also matching; create
+        // the ancestor directories on demand so such entries don't fail
+        // with a spurious "create file" error.
+        if matches!(entry.filetype, FileType::File | FileType::Symlink) {
+            if let Some(parent) = path.parent() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    on_error!("create parent directory", e)
+                }
+            }
+        }
+
         match entry.filetype {
             FileType::Dir => {
                 log::info!("Creating directory: {}", entry.name);
-                fs::create_dir(&path).expect("can't create directory")
+                match fs::create_dir(&path) {
+                    Ok(()) => {}
+                    Err(e)
+                        if options.allow_existing_dirs
+                            && e.kind() == io::ErrorKind::AlreadyExists => {}
+                    Err(e) => on_error!("create directory", e),
+                }
             }
             FileType::File => {
                 log::info!("Creating file: {} (size: {})", entry.name, entry.size);
-                let mut f = File::create(&path).expect("can't create file");
+                let mut f = match File::create(&path) {
+                    Ok(f) => f,
+                    Err(e) => on_error!("create file", e),
+                };
 
                 if let Some(data) = &entry.data {
-                    f.write_all(data).unwrap();
+                    if let Err(e) = f.write_all(data) {
+                        on_error!("write file", e)
+                    }
                 }
             }
+            FileType::Symlink => {
+                let target = entry
+                    .data
+                    .as_deref()
+                    .map(std::ffi::OsStr::from_bytes)
+                    .unwrap_or_default();
+                log::info!("Creating symlink: {} -> {:?}", entry.name, target);
+                if let Err(e) = symlink(target, &path) {
+                    on_error!("create symlink", e)
+                }
+                return Ok(());
+            }
             FileType::Unsupported => {
                 log::warn!("Unsupported file type for {}, skipping.", entry.name);
                 return Ok(());
@@ -154,21 +358,122 @@ pub fn extract(path: &Path, dest: &Path) -> Result<()> {
         }
 
         let ft = FileTime::from_unix_time(entry.mtime, 0);
-        filetime::set_file_mtime(&path, ft).unwrap();
+        if let Err(e) = filetime::set_file_mtime(&path, ft) {
+            on_error!("set mtime", e)
+        }
 
-        let attr = fs::metadata(&path).unwrap();
-        let mut permissions = attr.permissions();
-        permissions.set_mode(entry.mode);
-        fs::set_permissions(&path, permissions).unwrap();
+        match fs::metadata(&path) {
+            Ok(attr) => {
+                let mut permissions = attr.permissions();
+                permissions.set_mode(entry.mode);
+                if let Err(e) = fs::set_permissions(&path, permissions) {
+                    on_error!("set permissions", e)
+                }
+            }
+            Err(e) => on_error!("read metadata", e),
+        }
 
         Ok(())
     })?;
 
-    Ok(())
+    Ok(errors)
 }
 
-/// Extract all files from the SQLar at `path` into `dest`
+/// An error from [`extract_one`].
+#[derive(Debug)]
+pub enum ExtractOneError {
+    /// no entry with that name exists in the archive.
+    NotFound,
+    /// the entry exists but is a directory, not a file.
+    IsDirectory,
+    /// the archive couldn't be read.
+    Db(rusqlite::Error),
+    /// `writer` couldn't be written to.
+    Io(io::Error),
+}
+
+impl std::fmt::Display for ExtractOneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtractOneError::NotFound => write!(f, "no such entry in archive"),
+            ExtractOneError::IsDirectory => write!(f, "entry is a directory, not a file"),
+            ExtractOneError::Db(e) => write!(f, "{}", e),
+            ExtractOneError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExtractOneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExtractOneError::Db(e) => Some(e),
+            ExtractOneError::Io(e) => Some(e),
+            ExtractOneError::NotFound | ExtractOneError::IsDirectory => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ExtractOneError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => ExtractOneError::NotFound,
+            e => ExtractOneError::Db(e),
+        }
+    }
+}
+
+/// Extract exactly one entry, identified by its stored `name`, and write its
+/// decompressed bytes to `writer` without touching any other row.
+pub fn extract_one(
+    archive: &Path,
+    name: &str,
+    writer: &mut impl Write,
+) -> std::result::Result<(), ExtractOneError> {
+    let db = Connection::open(archive)?;
+    compress::init(&db, Compression::default())?;
+
+    let (mode, data): (u32, Vec<u8>) = db.query_row(
+        "SELECT mode, rusty_sqlar_uncompress(data, sz) FROM sqlar WHERE name = ?1",
+        params![name],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    if FileType::from(mode) == FileType::Dir {
+        return Err(ExtractOneError::IsDirectory);
+    }
+
+    writer.write_all(&data).map_err(ExtractOneError::Io)
+}
+
+/// Options controlling how [`create_with_options`]/[`add_with_options`]
+/// write new entries.
+pub struct CreateOptions {
+    /// codec used to compress newly written entries.
+    pub compression: Compression,
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        CreateOptions {
+            compression: Compression::default(),
+        }
+    }
+}
+
+/// Create a new archive and add all regular files, directories and symlinks
+/// found in `paths`. If `archive` already exists, prints an error to stderr
+/// and returns `Ok(())` without touching it; use [`add`] to append to an
+/// existing archive instead.
 pub fn create(archive: &Path, paths: &[PathBuf]) -> Result<()> {
+    create_with_options(archive, paths, &CreateOptions::default())
+}
+
+/// Like [`create`], but with control over the compression codec via `options`.
+pub fn create_with_options(
+    archive: &Path,
+    paths: &[PathBuf],
+    options: &CreateOptions,
+) -> Result<()> {
     if archive.exists() {
         eprintln!(
             "error: {} already exists. not creating a new one.",
@@ -178,11 +483,59 @@ pub fn create(archive: &Path, paths: &[PathBuf]) -> Result<()> {
     }
 
     let db = Connection::open(archive)?;
-    extract::init(&db)?;
+    compress::init(&db, options.compression)?;
+    db.execute(SCHEMA, [])?;
+
+    add_paths(&db, paths)
+}
+
+/// Add `paths` to the archive at `archive`, creating it first if it doesn't
+/// exist yet. An entry whose `name` already exists in the archive has its
+/// mode/mtime/size/data replaced in place.
+pub fn add(archive: &Path, paths: &[PathBuf]) -> Result<()> {
+    add_with_options(archive, paths, &CreateOptions::default())
+}
+
+/// Like [`add`], but with control over the compression codec via `options`.
+pub fn add_with_options(archive: &Path, paths: &[PathBuf], options: &CreateOptions) -> Result<()> {
+    let db = Connection::open(archive)?;
+    compress::init(&db, options.compression)?;
+    ensure_sqlar_table(&db)?;
     db.execute(SCHEMA, [])?;
 
+    add_paths(&db, paths)
+}
+
+/// Refuse to append to `db` if it's an existing, non-empty SQLite database
+/// that doesn't already have a `sqlar` table, so pointing `-a` at some
+/// unrelated database doesn't silently create one inside it.
+fn ensure_sqlar_table(db: &Connection) -> Result<()> {
+    let has_sqlar: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlar')",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_sqlar {
+        return Ok(());
+    }
+
+    let has_other_tables: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table')",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_other_tables {
+        return Err(rusqlite::Error::UserFunctionError(
+            "refusing to append: database already exists and has no sqlar table".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn add_paths(db: &Connection, paths: &[PathBuf]) -> Result<()> {
     for path in paths {
-        for entry in WalkDir::new(path) {
+        for entry in WalkDir::new(path).follow_links(false) {
             let entry = match entry {
                 Ok(entry) => entry,
                 Err(e) => {
@@ -224,6 +577,14 @@ pub fn create(archive: &Path, paths: &[PathBuf]) -> Result<()> {
                 }
 
                 data
+            } else if file_type == FileType::Symlink {
+                match fs::read_link(path) {
+                    Ok(target) => target.as_os_str().as_bytes().to_vec(),
+                    Err(e) => {
+                        log::warn!("could not read link {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
             } else {
                 vec![]
             };
@@ -240,7 +601,7 @@ pub fn create(archive: &Path, paths: &[PathBuf]) -> Result<()> {
 
             db.execute(
                 r#"
-                INSERT INTO
+                INSERT OR REPLACE INTO
                     sqlar (name, mode, mtime, sz, data)
                 VALUES
                     (?1, ?2, ?3, ?4, rusty_sqlar_compress(?5))
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use fuser::{
+    FileAttr, FileType as FuseFileType, Filesystem, MountOption, ReplyAttr, ReplyData,
+    ReplyDirectory, ReplyEntry, Request,
+};
+use libc::ENOENT;
+use rusqlite::{params, Connection};
+
+use crate::{with_each_file, FileType};
+
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+
+struct Node {
+    parent: u64,
+    name: String,
+    kind: FileType,
+    mode: u32,
+    mtime: i64,
+    size: u64,
+    children: HashMap<String, u64>,
+    /// The entry's exact `sqlar.name` value, used to look its data up
+    /// without reconstructing a path from inode components (which can
+    /// disagree with the stored name, e.g. for `./`-prefixed entries).
+    stored_name: Option<String>,
+}
+
+impl Node {
+    fn attr(&self, ino: u64) -> FileAttr {
+        let mtime = UNIX_EPOCH + Duration::from_secs(self.mtime.max(0) as u64);
+        FileAttr {
+            ino,
+            size: self.size,
+            blocks: (self.size + 511) / 512,
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind: match self.kind {
+                FileType::Dir => FuseFileType::Directory,
+                FileType::Symlink => FuseFileType::Symlink,
+                FileType::File | FileType::Unsupported => FuseFileType::RegularFile,
+            },
+            perm: (self.mode & 0o7777) as u16,
+            nlink: if self.kind == FileType::Dir { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+}
+
+/// A read-only FUSE view of a SQLar archive.
+///
+/// The inode tree is built once, up front, from a single non-decompressing
+/// pass over the archive. File contents are decompressed lazily on `read`,
+/// with the most recently decoded entry cached so sequential reads stay
+/// cheap.
+pub struct SqlarFs {
+    db: Connection,
+    nodes: HashMap<u64, Node>,
+    cache: Option<(u64, Vec<u8>)>,
+}
+
+impl SqlarFs {
+    /// Open `archive` and build the inode tree for it.
+    pub fn open(archive: &Path) -> crate::Result<SqlarFs> {
+        let db = Connection::open(archive)?;
+        crate::compress::init(&db, crate::Compression::default())?;
+
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            ROOT_INO,
+            Node {
+                parent: ROOT_INO,
+                name: String::new(),
+                kind: FileType::Dir,
+                mode: 0o755,
+                mtime: 0,
+                size: 0,
+                children: HashMap::new(),
+                stored_name: None,
+            },
+        );
+        let mut next_ino = ROOT_INO + 1;
+
+        with_each_file(&db, false, |entry| {
+            if !matches!(
+                entry.filetype,
+                FileType::Dir | FileType::File | FileType::Symlink
+            ) {
+                log::warn!("skipping unsupported entry in mount tree: {}", entry.name);
+                return Ok(());
+            }
+
+            let components: Vec<&str> = entry
+                .name
+                .split('/')
+                .filter(|c| !c.is_empty() && *c != ".")
+                .collect();
+            if components.is_empty() {
+                return Ok(());
+            }
+
+            let mut parent = ROOT_INO;
+            for component in &components[..components.len() - 1] {
+                parent = get_or_create_dir(&mut nodes, &mut next_ino, parent, component);
+            }
+
+            let name = components[components.len() - 1];
+            let ino = get_or_create_dir_slot(&mut nodes, &mut next_ino, parent, name);
+            let node = nodes.get_mut(&ino).expect("just inserted");
+            node.kind = entry.filetype;
+            node.mode = entry.mode;
+            node.mtime = entry.mtime;
+            node.size = entry.size as u64;
+            node.stored_name = Some(entry.name.clone());
+
+            Ok(())
+        })?;
+
+        Ok(SqlarFs {
+            db,
+            nodes,
+            cache: None,
+        })
+    }
+
+    fn read_data(&mut self, ino: u64) -> rusqlite::Result<&[u8]> {
+        if self.cache.as_ref().map(|(i, _)| *i) != Some(ino) {
+            let name = self.nodes[&ino]
+                .stored_name
+                .clone()
+                .expect("read_data is only called on entries backed by a sqlar row");
+            let data: Vec<u8> = self.db.query_row(
+                "SELECT rusty_sqlar_uncompress(data, sz) FROM sqlar WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )?;
+            self.cache = Some((ino, data));
+        }
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+fn get_or_create_dir(
+    nodes: &mut HashMap<u64, Node>,
+    next_ino: &mut u64,
+    parent: u64,
+    name: &str,
+) -> u64 {
+    let ino = get_or_create_dir_slot(nodes, next_ino, parent, name);
+    nodes.get_mut(&ino).expect("just inserted").kind = FileType::Dir;
+    ino
+}
+
+fn get_or_create_dir_slot(
+    nodes: &mut HashMap<u64, Node>,
+    next_ino: &mut u64,
+    parent: u64,
+    name: &str,
+) -> u64 {
+    if let Some(ino) = nodes[&parent].children.get(name) {
+        return *ino;
+    }
+
+    let ino = *next_ino;
+    *next_ino += 1;
+    nodes.insert(
+        ino,
+        Node {
+            parent,
+            name: name.to_string(),
+            kind: FileType::Dir,
+            mode: 0o755,
+            mtime: 0,
+            size: 0,
+            children: HashMap::new(),
+            stored_name: None,
+        },
+    );
+    nodes
+        .get_mut(&parent)
+        .expect("parent exists")
+        .children
+        .insert(name.to_string(), ino);
+    ino
+}
+
+impl Filesystem for SqlarFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => return reply.error(ENOENT),
+        };
+
+        let ino = self
+            .nodes
+            .get(&parent)
+            .and_then(|node| node.children.get(name))
+            .copied();
+
+        match ino {
+            Some(ino) => reply.entry(&TTL, &self.nodes[&ino].attr(ino), 0),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.nodes.get(&ino) {
+            Some(node) => reply.attr(&TTL, &node.attr(ino)),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let node = match self.nodes.get(&ino) {
+            Some(node) => node,
+            None => return reply.error(ENOENT),
+        };
+        if node.kind != FileType::Dir {
+            return reply.error(ENOENT);
+        }
+
+        let mut entries = vec![
+            (ino, FuseFileType::Directory, ".".to_string()),
+            (node.parent, FuseFileType::Directory, "..".to_string()),
+        ];
+        for (name, &child_ino) in &node.children {
+            let kind = match self.nodes[&child_ino].kind {
+                FileType::Dir => FuseFileType::Directory,
+                FileType::Symlink => FuseFileType::Symlink,
+                FileType::File | FileType::Unsupported => FuseFileType::RegularFile,
+            };
+            entries.push((child_ino, kind, name.clone()));
+        }
+
+        for (i, (child_ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(child_ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let is_dir = match self.nodes.get(&ino) {
+            Some(node) => node.kind == FileType::Dir,
+            None => return reply.error(ENOENT),
+        };
+        if is_dir {
+            return reply.error(ENOENT);
+        }
+
+        match self.read_data(ino) {
+            Ok(data) => {
+                let offset = offset.max(0) as usize;
+                let end = (offset + size as usize).min(data.len());
+                if offset >= data.len() {
+                    reply.data(&[]);
+                } else {
+                    reply.data(&data[offset..end]);
+                }
+            }
+            Err(e) => {
+                log::warn!(
+                    "failed to decompress entry {}: {}",
+                    self.nodes[&ino].name,
+                    e
+                );
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: fuser::ReplyData) {
+        let is_symlink = match self.nodes.get(&ino) {
+            Some(node) => node.kind == FileType::Symlink,
+            None => return reply.error(ENOENT),
+        };
+        if !is_symlink {
+            return reply.error(ENOENT);
+        }
+
+        match self.read_data(ino) {
+            Ok(target) => reply.data(target),
+            Err(e) => {
+                log::warn!(
+                    "failed to read symlink target for {}: {}",
+                    self.nodes[&ino].name,
+                    e
+                );
+                reply.error(libc::EIO);
+            }
+        }
+    }
+}
+
+/// Mount the SQLar archive at `archive` read-only at `mountpoint`.
+///
+/// Blocks the calling thread until the filesystem is unmounted.
+pub fn mount(archive: &Path, mountpoint: &Path) -> crate::Result<()> {
+    let fs = SqlarFs::open(archive)?;
+    let options = vec![
+        MountOption::RO,
+        MountOption::FSName("sqlar".to_string()),
+    ];
+
+    fuser::mount2(fs, mountpoint, &options).map_err(|e| {
+        rusqlite::Error::UserFunctionError(format!("failed to mount: {}", e).into())
+    })
+}